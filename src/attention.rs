@@ -2,7 +2,7 @@ use burn::nn::{Initializer, Linear, LinearConfig, Dropout, DropoutConfig};
 use burn::{
     config::Config,
     module::Module,
-    tensor::{activation, backend::Backend, Bool, Tensor, Int},
+    tensor::{activation, backend::Backend, Bool, DType, Element, ElementConversion, Tensor, Int},
 };
 // use libm::sqrtf;
 
@@ -13,79 +13,351 @@ pub struct HeadConfig {
     block_size: usize, 
     n_embd: usize, 
     head_size: usize, 
-    dropout: f64, 
+    dropout: f64,
     /// The type of function used to initialize neural network parameters
     #[config(
         default = "Initializer::KaimingUniform{gain:1.0/libm::sqrt(3.0), fan_out_only:false}"
     )]
     pub initializer: Initializer,
+    /// When enabled, replaces the softmax over attention weights with softmax-off-by-one
+    /// (a.k.a. "quiet softmax"), which lets a head attend to nothing by appending an
+    /// implicit zero logit to each row. Defaults to `false` to preserve current behavior.
+    #[config(default = false)]
+    pub quiet_softmax: bool,
+    /// When enabled, rotates each query/key vector by its absolute time index before
+    /// computing attention weights (rotary position embeddings). This makes attention
+    /// scores depend only on relative offsets, improving generalization to longer
+    /// contexts than learned absolute position embeddings. Defaults to `false`.
+    #[config(default = false)]
+    pub use_rope: bool,
+    /// When set to `w`, each query additionally only attends to keys within `w` steps
+    /// of itself (a banded causal mask keeping positions `j` where `q - w < j <= q`),
+    /// instead of the full preceding history. Defaults to `None` (no windowing).
+    #[config(default = "None")]
+    pub local_window: Option<usize>,
+}
+
+/// Builds the causal attention mask shared by [`HeadConfig::init`] and
+/// [`MultiHeadAttentionConfig::init_fused`]: `true` at positions that must be
+/// masked out (the current and future positions, and, when `local_window` is set,
+/// keys further than `w` steps in the past).
+fn causal_mask<B: Backend>(
+    batch_size: usize,
+    block_size: usize,
+    local_window: Option<usize>,
+    device: &B::Device,
+) -> Tensor<B, 3, Bool> {
+    let tril: Tensor<B, 3, Int> = Tensor::ones([batch_size, block_size, block_size], device).tril(-1);
+    let mask = tril.equal_elem(0);
+
+    match local_window {
+        Some(w) => {
+            let too_far_past: Tensor<B, 3, Int> = Tensor::ones([batch_size, block_size, block_size], device)
+                .tril(-(w as i64) - 1);
+            let too_far_past = too_far_past.equal_elem(1);
+            mask.bool_or(too_far_past)
+        }
+        None => mask,
+    }
+}
+
+/// Attention score scale divisor `sqrt(head_size)`, converted into the active backend
+/// element type so it carries the right precision whether attention runs in `f32`,
+/// `f16`, or `bf16`.
+fn attn_scale<E: Element>(head_size: usize) -> E {
+    (head_size as f64).sqrt().elem()
+}
+
+/// Largest-magnitude safe negative value to mask out attention logits with. Full
+/// `f32`/`f64`/`bf16` precision keeps the original `-1.0e4` (`bf16` shares `f32`'s
+/// exponent range, so it's no more prone to overflow), but `f16`'s narrower exponent
+/// range needs a smaller magnitude so masked logits don't saturate towards `-inf` and
+/// produce NaNs once softmax exponentiates them.
+fn mask_fill_value<E: Element>() -> E {
+    let value: f64 = if E::dtype() == DType::F16 { -1.0e3 } else { -1.0e4 };
+    value.elem()
+}
+
+/// Shared softmax step used by both per-head and fused attention, and by their cached
+/// counterparts, so `quiet_softmax` behaves identically regardless of which path a
+/// `HeadConfig` is initialized through.
+fn attend_softmax<B: Backend>(wei: Tensor<B, 3>, quiet_softmax: bool) -> Tensor<B, 3> {
+    if quiet_softmax {
+        // softmax-off-by-one: append an implicit zero logit to each row so a query can
+        // attend to "nothing" instead of being forced to distribute full mass. The zero
+        // logit is concatenated in *before* the max/exp-sum reduction (rather than
+        // computed separately as exp(-m) against the raw row max) so a fully-masked row
+        // - whose only real logit is the mask-fill constant itself - takes its max from
+        // the zero column instead of exponentiating the mask-fill constant's negation,
+        // which would overflow for large fill magnitudes.
+        let [b, t, tk] = wei.dims();
+        let device = wei.device();
+        let zeros = Tensor::zeros([b, t, 1], &device);
+        let wei_ext = Tensor::cat(vec![wei, zeros], 2);
+        let m = wei_ext.clone().max_dim(2);
+        let exp = (wei_ext - m).exp();
+        let denominator = exp.clone().sum_dim(2);
+        // drop the implicit zero-logit column now that it's folded into the denominator
+        let numerator = exp.slice([0..b, 0..t, 0..tk]);
+        numerator / denominator
+    } else {
+        // ref https://docs.rs/burn/0.9.0/burn/tensor/activation/fn.softmax.html
+        activation::softmax(wei, 2)
+    }
+}
+
+/// Precomputes rotary position embedding inverse frequencies: `inv_freq[i] =
+/// 10000^(-2i/head_size)` for `i` in `0..head_size/2`.
+fn rope_inv_freq<B: Backend>(head_size: usize, device: &B::Device) -> Tensor<B, 1> {
+    let i: Tensor<B, 1> = Tensor::<B, 1, Int>::arange(0..(head_size / 2) as i64, device).float();
+    (i * (-2.0 * 10000f32.ln() / head_size as f32)).exp()
+}
+
+/// Applies rotary position embeddings to a `(B, T, hs)` query/key tensor, rotating each
+/// dimension pair `(2i, 2i+1)` by the angle `theta = t * inv_freq[i]` where `t` is the
+/// absolute time index `offset..offset+T`. Shared by per-head and fused attention so
+/// `use_rope` behaves identically regardless of which path a `HeadConfig` is
+/// initialized through.
+fn rotate<B: Backend>(x: Tensor<B, 3>, offset: usize, inv_freq: &Tensor<B, 1>) -> Tensor<B, 3> {
+    let [b, t, hs] = x.dims();
+    let device = x.device();
+
+    let pos: Tensor<B, 1> = Tensor::<B, 1, Int>::arange(offset as i64..(offset + t) as i64, &device).float();
+    // (1, T, hs/2)
+    let theta = pos.reshape([1, t, 1]) * inv_freq.clone().reshape([1, 1, hs / 2]);
+    let cos = theta.clone().cos();
+    let sin = theta.sin();
+
+    // group the last dim into (hs/2, 2) pairs: (x_2i, x_2i+1)
+    let pairs = x.reshape([b, t, hs / 2, 2]);
+    let x_even = pairs.clone().slice([0..b, 0..t, 0..hs / 2, 0..1]).reshape([b, t, hs / 2]);
+    let x_odd = pairs.slice([0..b, 0..t, 0..hs / 2, 1..2]).reshape([b, t, hs / 2]);
+
+    let rotated_even = x_even.clone() * cos.clone() - x_odd.clone() * sin.clone();
+    let rotated_odd = x_even * sin + x_odd * cos;
+
+    Tensor::cat(
+        vec![
+            rotated_even.reshape([b, t, hs / 2, 1]),
+            rotated_odd.reshape([b, t, hs / 2, 1]),
+        ],
+        3,
+    )
+    .reshape([b, t, hs])
+}
+
+/// Truncates cached key/value tensors to the most recent `local_window` entries along
+/// the time dimension (a no-op when `local_window` is `None`), so the incremental
+/// cached paths bound their attention footprint the same way `causal_mask`'s banded
+/// mask bounds the batched-forward paths.
+fn truncate_to_window<B: Backend>(
+    k: Tensor<B, 3>,
+    v: Tensor<B, 3>,
+    local_window: Option<usize>,
+) -> (Tensor<B, 3>, Tensor<B, 3>) {
+    match local_window {
+        Some(w) => {
+            let [b, t, hs] = k.dims();
+            if t > w {
+                let start = t - w;
+                (
+                    k.slice([0..b, start..t, 0..hs]),
+                    v.slice([0..b, start..t, 0..hs]),
+                )
+            } else {
+                (k, v)
+            }
+        }
+        None => (k, v),
+    }
 }
 
 impl HeadConfig {
     pub fn init<B: Backend>(&self, device: &B::Device) -> Head<B> {
-        // compute the weight matrix 
-        let tril: Tensor<B, 3, Int> = Tensor::ones(
-            [self.batch_size, self.block_size, self.block_size], 
-            device, 
-        ).tril(-1);
-        let tril = tril.equal_elem(0); 
+        // compute the weight matrix
+        let tril = causal_mask::<B>(self.batch_size, self.block_size, self.local_window, device);
+
+        if self.use_rope {
+            assert_eq!(
+                self.head_size % 2,
+                0,
+                "RoPE requires an even head_size (got {})",
+                self.head_size
+            );
+        }
+        let inv_freq = rope_inv_freq::<B>(self.head_size, device);
 
         Head {
             key: LinearConfig::new(
-                self.n_embd, 
-                self.head_size, 
+                self.n_embd,
+                self.head_size,
             ).with_initializer(self.initializer.clone())
             .init(device),
             query: LinearConfig::new(
-                self.n_embd, 
-                self.head_size, 
+                self.n_embd,
+                self.head_size,
             ).with_initializer(self.initializer.clone())
             .init(device),
             value: LinearConfig::new(
-                self.n_embd, 
-                self.head_size, 
+                self.n_embd,
+                self.head_size,
             ).with_initializer(self.initializer.clone())
             .init(device),
-            tril, 
+            tril,
             dropout: DropoutConfig::new(self.dropout).init(),
+            quiet_softmax: self.quiet_softmax,
+            use_rope: self.use_rope,
+            inv_freq,
+            local_window: self.local_window,
         }
     }
 }
 
 #[derive(Module, Debug)]
 pub struct Head<B: Backend> {
-    query: Linear<B>, 
-    key: Linear<B>, 
-    value: Linear<B>, 
-    tril: Tensor<B, 3, Bool>, 
-    dropout: Dropout, 
+    query: Linear<B>,
+    key: Linear<B>,
+    value: Linear<B>,
+    tril: Tensor<B, 3, Bool>,
+    dropout: Dropout,
+    quiet_softmax: bool,
+    use_rope: bool,
+    /// Precomputed inverse frequencies for rotary position embeddings, shape `(head_size / 2,)`.
+    inv_freq: Tensor<B, 1>,
+    /// Mirrors `HeadConfig::local_window` so `forward_cached` bounds its cache the same
+    /// way `forward`'s banded causal mask bounds batched attention.
+    local_window: Option<usize>,
 }
 
 impl<B: Backend> Head<B> {
-    /// Single head attention 
+    /// Single head attention
     /// input of size (batch, time-step, channels)
     /// output of size (batch, time-step, head size)
-    pub fn forward(&self, x: Tensor<B, 3>) -> Tensor<B, 3> { 
+    pub fn forward(&self, x: Tensor<B, 3>) -> Tensor<B, 3> {
         // (B,T,hs)
-        let k = self.key.forward(x.clone()); 
+        let k = self.key.forward(x.clone());
         // (B,T,hs)
-        let q = self.query.forward(x.clone()); 
+        let q = self.query.forward(x.clone());
+        let (q, k) = if self.use_rope {
+            (self.rotate(q, 0), self.rotate(k, 0))
+        } else {
+            (q, k)
+        };
 
         // (B, T, hs) @ (B, hs, T) -> (B, T, T)
-        let wei = (q * k.clone().transpose()) / ((k.dims()[2] as f32).sqrt()); 
+        let wei = (q * k.clone().transpose()) / attn_scale::<B::FloatElem>(k.dims()[2]);
         // (B, T, T)
         // ref https://docs.rs/burn/0.9.0/burn/tensor/struct.Tensor.html#method.mask_fill
         // A value too low might result in NaN
-        let wei = wei.mask_fill(self.tril.clone(), -1.0e4); 
+        let wei = wei.mask_fill(self.tril.clone(), mask_fill_value::<B::FloatElem>());
         // (B, T, T)
-        // ref https://docs.rs/burn/0.9.0/burn/tensor/activation/fn.softmax.html
-        let wei = activation::softmax(wei, 2); 
+        let wei = self.attend(wei);
         let wei = self.dropout.forward(wei);
         // (B,T,hs)
-        let v = self.value.forward(x); 
+        let v = self.value.forward(x);
         // (B, T, T) @ (B, T, hs) -> (B, T, hs)
-        let out = wei * v;  
-        out 
+        let out = wei * v;
+        out
+    }
+
+    /// Incremental step for autoregressive generation: `x` holds exactly one new token,
+    /// the cached keys/values are extended in place, and the new query attends over the
+    /// cached history instead of recomputing it. This makes a sampling loop linear per
+    /// step instead of quadratic. When `local_window` is set, the cache is truncated to
+    /// the window's width every step, so it never grows past that bound.
+    ///
+    /// Only single-token steps are supported: without the causal mask used by
+    /// `forward`, a multi-token `x` would let its queries attend to later tokens within
+    /// the same call.
+    pub fn forward_cached(&self, x: Tensor<B, 3>, cache: &mut HeadCache<B>) -> Tensor<B, 3> {
+        let new_t = x.dims()[1];
+        assert_eq!(
+            new_t, 1,
+            "Head::forward_cached only supports single-token decoding steps (got {new_t} new tokens)"
+        );
+        // absolute position of the new token: however many are already cached
+        let offset = cache.key.as_ref().map(|k| k.dims()[1]).unwrap_or(0);
+
+        // (B, 1, hs)
+        let k_new = self.key.forward(x.clone());
+        let q = self.query.forward(x.clone());
+        let v_new = self.value.forward(x);
+        let (q, k_new) = if self.use_rope {
+            (self.rotate(q, offset), self.rotate(k_new, offset))
+        } else {
+            (q, k_new)
+        };
+
+        let k = match cache.key.take() {
+            Some(k_prev) => Tensor::cat(vec![k_prev, k_new], 1),
+            None => k_new,
+        };
+        let v = match cache.value.take() {
+            Some(v_prev) => Tensor::cat(vec![v_prev, v_new], 1),
+            None => v_new,
+        };
+        // bound the cache (and the attention footprint below) to the banded window,
+        // mirroring `forward`'s local_window masking instead of growing unboundedly
+        let (k, v) = truncate_to_window(k, v, self.local_window);
+
+        // (B, new_t, hs) @ (B, hs, T) -> (B, new_t, T)
+        let wei = (q * k.clone().transpose()) / attn_scale::<B::FloatElem>(k.dims()[2]);
+        // no causal mask needed: the cache only ever holds past and current positions
+        let wei = self.attend(wei);
+        let wei = self.dropout.forward(wei);
+        // (B, new_t, T) @ (B, T, hs) -> (B, new_t, hs)
+        let out = wei * v.clone();
+
+        cache.key = Some(k);
+        cache.value = Some(v);
+        out
+    }
+
+    /// Shared softmax step used by both [`Head::forward`] and [`Head::forward_cached`].
+    fn attend(&self, wei: Tensor<B, 3>) -> Tensor<B, 3> {
+        attend_softmax(wei, self.quiet_softmax)
+    }
+
+    /// Applies rotary position embeddings to a `(B, T, hs)` query/key tensor; see
+    /// [`rotate`] for the rotation itself.
+    fn rotate(&self, x: Tensor<B, 3>, offset: usize) -> Tensor<B, 3> {
+        rotate(x, offset, &self.inv_freq)
+    }
+}
+
+/// Per-head cache of keys and values accumulated across incremental decoding steps.
+/// Starts empty and grows by one time-step each call to [`Head::forward_cached`]; when
+/// `local_window` is set, it holds at most that many time-steps.
+#[derive(Debug, Clone)]
+pub struct HeadCache<B: Backend> {
+    key: Option<Tensor<B, 3>>,
+    value: Option<Tensor<B, 3>>,
+}
+
+impl<B: Backend> HeadCache<B> {
+    pub fn new() -> Self {
+        Self { key: None, value: None }
+    }
+}
+
+impl<B: Backend> Default for HeadCache<B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Per-head cache vector for [`MultiHeadAttention::forward_cached`], one [`HeadCache`]
+/// per head.
+#[derive(Debug, Clone)]
+pub struct MultiHeadAttentionCache<B: Backend> {
+    heads: Vec<HeadCache<B>>,
+}
+
+impl<B: Backend> MultiHeadAttentionCache<B> {
+    pub fn new(n_head: usize) -> Self {
+        Self {
+            heads: (0..n_head).map(|_| HeadCache::new()).collect(),
+        }
     }
 }
 
@@ -106,31 +378,236 @@ impl MultiHeadAttentionConfig {
 
         MultiHeadAttention {
             proj: LinearConfig::new(
-                self.head_size * self.n_head, 
-                self.n_embd, 
+                self.head_size * self.n_head,
+                self.n_embd,
             ).init(device),
-            dropout: DropoutConfig::new(self.dropout).init(), 
+            dropout: DropoutConfig::new(self.dropout).init(),
             heads: layers,
+            qkv: None,
+            attn_dropout: None,
+            tril: None,
+            n_head: self.n_head,
+            head_size: self.head_size,
+            quiet_softmax: head_config.quiet_softmax,
+            use_rope: head_config.use_rope,
+            inv_freq: rope_inv_freq::<B>(self.head_size, device),
+            local_window: head_config.local_window,
+        }
+    }
+
+    /// Fused alternative to [`MultiHeadAttentionConfig::init`]: instead of a `Vec<Head>`
+    /// running `3 * n_head` small matmuls per forward pass, this collapses the Q/K/V
+    /// projections of every head into a single `n_embd -> 3 * n_head * head_size`
+    /// linear, applied once, then split back into per-head Q, K, V tensors.
+    pub fn init_fused<B: Backend>(&self, device: &B::Device, head_config: &HeadConfig) -> MultiHeadAttention<B> {
+        // compute the weight matrix, shared across heads
+        let tril = causal_mask::<B>(
+            head_config.batch_size,
+            head_config.block_size,
+            head_config.local_window,
+            device,
+        );
+
+        if head_config.use_rope {
+            assert_eq!(
+                self.head_size % 2,
+                0,
+                "RoPE requires an even head_size (got {})",
+                self.head_size
+            );
+        }
+
+        MultiHeadAttention {
+            proj: LinearConfig::new(
+                self.head_size * self.n_head,
+                self.n_embd,
+            ).init(device),
+            dropout: DropoutConfig::new(self.dropout).init(),
+            heads: Vec::new(),
+            qkv: Some(
+                LinearConfig::new(self.n_embd, 3 * self.n_head * self.head_size)
+                    .with_initializer(head_config.initializer.clone())
+                    .init(device),
+            ),
+            attn_dropout: Some(DropoutConfig::new(head_config.dropout).init()),
+            tril: Some(tril),
+            n_head: self.n_head,
+            head_size: self.head_size,
+            quiet_softmax: head_config.quiet_softmax,
+            use_rope: head_config.use_rope,
+            inv_freq: rope_inv_freq::<B>(self.head_size, device),
+            local_window: head_config.local_window,
         }
     }
 }
 
 #[derive(Module, Debug)]
 pub struct MultiHeadAttention<B: Backend> {
-    proj: Linear<B>, 
-    dropout: Dropout, 
-    heads: Vec<Head<B>>, 
+    proj: Linear<B>,
+    dropout: Dropout,
+    heads: Vec<Head<B>>,
+    /// Fused Q/K/V projection used instead of `heads` when initialized via `init_fused`.
+    qkv: Option<Linear<B>>,
+    attn_dropout: Option<Dropout>,
+    tril: Option<Tensor<B, 3, Bool>>,
+    n_head: usize,
+    head_size: usize,
+    /// Mirrors `HeadConfig::quiet_softmax`/`HeadConfig::use_rope` so the fused path
+    /// (`forward_fused`/`forward_fused_cached`) stays in parity with per-head attention.
+    quiet_softmax: bool,
+    use_rope: bool,
+    inv_freq: Tensor<B, 1>,
+    /// Mirrors `HeadConfig::local_window` so `forward_fused_cached` bounds its cache
+    /// the same way `forward_fused`'s banded causal mask bounds batched attention.
+    local_window: Option<usize>,
 }
 
 impl<B: Backend> MultiHeadAttention<B> {
     pub fn forward(&self, x: Tensor<B, 3>) -> Tensor<B, 3> {
-        let mut inputs = Vec::new(); 
-        for head in self.heads.iter() {
-            inputs.push(head.forward(x.clone())); 
+        let x = match &self.qkv {
+            Some(qkv) => self.forward_fused(x, qkv),
+            None => {
+                let mut inputs = Vec::new();
+                for head in self.heads.iter() {
+                    inputs.push(head.forward(x.clone()));
+                }
+                Tensor::cat(inputs, 2)
+            }
+        };
+        let x = self.proj.forward(x);
+        let x = self.dropout.forward(x);
+        x
+    }
+
+    /// Incremental step for autoregressive generation: mirrors [`Head::forward_cached`]
+    /// across every head (or, when initialized via `init_fused`, the fused Q/K/V
+    /// projection), growing each head's slot in `cache` by the new token.
+    pub fn forward_cached(&self, x: Tensor<B, 3>, cache: &mut MultiHeadAttentionCache<B>) -> Tensor<B, 3> {
+        let x = match &self.qkv {
+            Some(qkv) => self.forward_fused_cached(x, qkv, cache),
+            None => {
+                let mut inputs = Vec::new();
+                for (head, head_cache) in self.heads.iter().zip(cache.heads.iter_mut()) {
+                    inputs.push(head.forward_cached(x.clone(), head_cache));
+                }
+                Tensor::cat(inputs, 2)
+            }
+        };
+        let x = self.proj.forward(x);
+        let x = self.dropout.forward(x);
+        x
+    }
+
+    /// Runs every head's attention from a single batched Q/K/V projection instead of
+    /// looping over per-head `Linear`s, reshaping the fused output into per-head
+    /// `(B, T, head_size)` slices before the usual masked-softmax attention.
+    fn forward_fused(&self, x: Tensor<B, 3>, qkv: &Linear<B>) -> Tensor<B, 3> {
+        let [b, t, _] = x.dims();
+        let n_head = self.n_head;
+        let head_size = self.head_size;
+        let tril = self.tril.clone().unwrap();
+
+        // (B, T, 3 * n_head * head_size)
+        let qkv_out = qkv.forward(x);
+        // (B, T, 3, n_head, head_size)
+        let qkv_out = qkv_out.reshape([b, t, 3, n_head, head_size]);
+
+        let mut inputs = Vec::new();
+        for h in 0..n_head {
+            // (B, T, head_size)
+            let qh = qkv_out.clone()
+                .slice([0..b, 0..t, 0..1, h..h + 1, 0..head_size])
+                .reshape([b, t, head_size]);
+            let kh = qkv_out.clone()
+                .slice([0..b, 0..t, 1..2, h..h + 1, 0..head_size])
+                .reshape([b, t, head_size]);
+            let vh = qkv_out.clone()
+                .slice([0..b, 0..t, 2..3, h..h + 1, 0..head_size])
+                .reshape([b, t, head_size]);
+            let (qh, kh) = if self.use_rope {
+                (rotate(qh, 0, &self.inv_freq), rotate(kh, 0, &self.inv_freq))
+            } else {
+                (qh, kh)
+            };
+
+            // (B, T, hs) @ (B, hs, T) -> (B, T, T)
+            let wei = (qh * kh.transpose()) / attn_scale::<B::FloatElem>(head_size);
+            let wei = wei.mask_fill(tril.clone(), mask_fill_value::<B::FloatElem>());
+            let wei = attend_softmax(wei, self.quiet_softmax);
+            let wei = self.attn_dropout.as_ref().unwrap().forward(wei);
+            // (B, T, T) @ (B, T, hs) -> (B, T, hs)
+            inputs.push(wei * vh);
+        }
+        Tensor::cat(inputs, 2)
+    }
+
+    /// Incremental fused-path counterpart to [`MultiHeadAttention::forward_fused`]: each
+    /// head's Q/K/V is sliced out of a single batched projection, same as
+    /// `forward_fused`, but K/V are cached and extended one step at a time like
+    /// [`Head::forward_cached`], with each head's cache truncated to `local_window` the
+    /// same way. Only single-token decoding steps are supported.
+    fn forward_fused_cached(
+        &self,
+        x: Tensor<B, 3>,
+        qkv: &Linear<B>,
+        cache: &mut MultiHeadAttentionCache<B>,
+    ) -> Tensor<B, 3> {
+        let [b, t, _] = x.dims();
+        assert_eq!(
+            t, 1,
+            "MultiHeadAttention::forward_cached only supports single-token decoding steps (got {t} new tokens)"
+        );
+        let n_head = self.n_head;
+        let head_size = self.head_size;
+
+        // (B, 1, 3 * n_head * head_size)
+        let qkv_out = qkv.forward(x);
+        // (B, 1, 3, n_head, head_size)
+        let qkv_out = qkv_out.reshape([b, t, 3, n_head, head_size]);
+
+        let mut inputs = Vec::new();
+        for (h, head_cache) in cache.heads.iter_mut().enumerate() {
+            let offset = head_cache.key.as_ref().map(|k| k.dims()[1]).unwrap_or(0);
+
+            // (B, 1, head_size)
+            let qh = qkv_out.clone()
+                .slice([0..b, 0..t, 0..1, h..h + 1, 0..head_size])
+                .reshape([b, t, head_size]);
+            let kh_new = qkv_out.clone()
+                .slice([0..b, 0..t, 1..2, h..h + 1, 0..head_size])
+                .reshape([b, t, head_size]);
+            let vh_new = qkv_out.clone()
+                .slice([0..b, 0..t, 2..3, h..h + 1, 0..head_size])
+                .reshape([b, t, head_size]);
+            let (qh, kh_new) = if self.use_rope {
+                (rotate(qh, offset, &self.inv_freq), rotate(kh_new, offset, &self.inv_freq))
+            } else {
+                (qh, kh_new)
+            };
+
+            let kh = match head_cache.key.take() {
+                Some(k_prev) => Tensor::cat(vec![k_prev, kh_new], 1),
+                None => kh_new,
+            };
+            let vh = match head_cache.value.take() {
+                Some(v_prev) => Tensor::cat(vec![v_prev, vh_new], 1),
+                None => vh_new,
+            };
+            // bound the cache (and the attention footprint below) to the banded window,
+            // mirroring `forward_fused`'s local_window masking instead of growing unboundedly
+            let (kh, vh) = truncate_to_window(kh, vh, self.local_window);
+
+            // (B, 1, hs) @ (B, hs, T) -> (B, 1, T)
+            let wei = (qh * kh.clone().transpose()) / attn_scale::<B::FloatElem>(head_size);
+            // no causal mask needed: the cache only ever holds past and current positions
+            let wei = attend_softmax(wei, self.quiet_softmax);
+            let wei = self.attn_dropout.as_ref().unwrap().forward(wei);
+            // (B, 1, T) @ (B, T, hs) -> (B, 1, hs)
+            inputs.push(wei * vh.clone());
+
+            head_cache.key = Some(kh);
+            head_cache.value = Some(vh);
         }
-        let x = Tensor::cat(inputs, 2); 
-        let x = self.proj.forward(x); 
-        let x = self.dropout.forward(x); 
-        x  
+        Tensor::cat(inputs, 2)
     }
 }
\ No newline at end of file